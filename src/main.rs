@@ -5,7 +5,7 @@ fn main() {
     // i.e. 0 -> read, 1 -> write ...
     // this is required since we only get the id of the syscall that was triggered not its name
     // so it helps in making the output more human readable
-    let syscall_table: std::collections::HashMap<u64, String> = syscalls::fetch_syscall_table();
+    let syscall_table: std::collections::HashMap<u64, syscalls::SyscallInfo> = syscalls::fetch_syscall_table();
 
     // `tracee_pid` will store the pid of the process that we want to talk
     // we can either fork our own process `stalker ls -al` or pass an explicit pid `stalker -p 123`
@@ -14,10 +14,25 @@ fn main() {
 
     // Fetch the commandline arguments this was invoked with
     // and skip the first element since that will be this utility "stalker" itself
-    let cmd: Vec<String> = std::env::args()
+    let mut cmd: Vec<String> = std::env::args()
         .skip(1)
         .collect();
 
+    // Pull an optional `-e trace=...` filter expression out of the arguments before anything else
+    // looks at `cmd`, so `stalker -e trace=open,read,write ls -al` still hands `ls -al` onward
+    // untouched, same as the existing `-p` handling below. Only recognized as `cmd[0]`/`cmd[1]`,
+    // same as `-p` below, so `stalker sed -e 's/a/b/' file.txt` doesn't have the traced command's
+    // own `-e` stripped out from under it. `negated` flips the match below for `-e trace=!close`,
+    // and the whole filter is `None` when no `-e` was given at all
+    let trace_filter: Option<(bool, std::collections::HashSet<u64>)> =
+        if cmd.len() >= 2 && cmd[0] == "-e" {
+            cmd.remove(0); // Remove `-e`
+            let expr = cmd.remove(0); // Remove and take `trace=...`
+            Some(syscalls::parse_trace_filter(&expr, &syscall_table))
+        } else {
+            None
+        };
+
     // Check if the arguments are in `-p 123` format or not
     // i.e. has a specific pid been provided to track
     if cmd.len() == 2 && cmd[0] == "-p" { // If a specific pid has been provided
@@ -119,65 +134,199 @@ fn main() {
     // If we've reached this point, that means the state of the `tracee` has changed
     // and we're ready to start tracing from here on
 
-    // `is_sys_exit` is a toggle variable
-    // we will essentially be notified about each system call twice, once during `entry` and again during `exit`
-    // this will lead to duplication in our trace, so we use this toggle to ensure we only trace alternative system calls
-    // only during exit to be precise since that will also give us the resulting value of the system call
-    let mut is_sys_exit: bool = false;
+    // Ask the kernel to also stop us whenever the tracee forks/vforks/clones off a child (or execs),
+    // instead of only ever seeing the single process we started with. `PTRACE_SETOPTIONS` is ptrace
+    // request `0x4200`, the `data` argument carries the bitmask of options to turn on:
+    // `PTRACE_O_TRACEFORK` (0x2), `PTRACE_O_TRACEVFORK` (0x4), `PTRACE_O_TRACECLONE` (0x8), `PTRACE_O_TRACEEXEC` (0x10)
+    // and `PTRACE_O_TRACESYSGOOD` (0x1), which makes syscall-stops arrive as `SIGTRAP | 0x80` instead
+    // of a plain `SIGTRAP`, so they can be told apart from an ordinary trap/signal stop by masking
+    // the stop signal out of the wait status
+    _ = syscalls::sys_ptrace(0x4200, tracee_pid as i64, 0, 0x1 | 0x2 | 0x4 | 0x8 | 0x10);
 
-    // This is the main tracing loop, we keep running it until the `tracee` exits
-    loop {
-        // Trigger a `ptrace` system call, this specific one tells the kernel to inform us when our tracee reaches a system call invocation
-        // this included both, entry to the system call as well as exit, this is why we require the `is_sys_exit` toggle flag above
-        // this will essentially trap the tracee with a `SIGTRAP` and return control to us
-        _ = syscalls::sys_ptrace(24, tracee_pid as i64, 0, 0);
+    // Tracees we're currently tracking, used to know when to stop the tracer, previously an
+    // `is_sys_exit` toggle map was also needed here to guess entry vs. exit, but `PTRACE_GET_SYSCALL_INFO`
+    // below tells us that authoritatively so the fragile toggle isn't needed anymore
+    let mut tracees: std::collections::HashSet<u64> = std::collections::HashSet::new();
+    tracees.insert(tracee_pid);
 
-        // Trigger a `wait4` system call, due to the previous `ptrace` system call invocation,
-        // we will be notified once the tracee reaches a system call and control will be passed to us, hence we wait till then
-        _ = syscalls::sys_wait4(tracee_pid as i64, &mut status as *mut i64 as i64, 0, 0);
+    // Kick off tracing for the original tracee, the main loop below takes over from here for it
+    // as well as for every descendant it spawns
+    _ = syscalls::sys_ptrace(24, tracee_pid as i64, 0, 0);
 
-        // If we've reached here, that means the `tracee` has reached a system call/or the child has exited
+    // This is the main tracing loop, we keep running it until every tracee we know about has exited
+    loop {
+        // Trigger a `wait4` system call for pid `-1`, meaning "any child of ours", instead of a single
+        // fixed pid, since we now need to hear about state changes from the original tracee and any
+        // fork/vfork/clone descendant of it at the same time
+        let pid: i64 = syscalls::sys_wait4(-1, &mut status as *mut i64 as i64, 0, 0);
+        if pid < 0 { // No tracees left to wait on
+            break;
+        }
+        let pid: u64 = pid as u64;
 
-        // Check if the `tracee` has exited or not
+        // Check if this particular tracee has exited or not
         if (status & 0x7f) == 0 { // If it has exited
-            // Log the exit status and break out of the loop, i.e. gracefully exit the `tracer` as well since our work is done
-            println!("child exited with status: {}", (status >> 8) & 0xff);
-            break;
+            // Log the exit status, drop it from the set of tracees we're tracking, and only stop
+            // the tracer once nothing is left to trace
+            println!("[{}] child exited with status: {}", pid, (status >> 8) & 0xff);
+            tracees.remove(&pid);
+            if tracees.is_empty() {
+                break;
+            }
+            continue;
+        }
+
+        // A tracee killed by a signal (`WIFSIGNALED`) hasn't stopped either (`WIFSTOPPED` is
+        // `(status & 0xff) == 0x7f`), so it's gone just like an exited one and must not reach the
+        // stop handling below
+        if (status & 0xff) != 0x7f {
+            println!("[{}] child killed by signal: {}", pid, syscalls::signal_name((status & 0x7f) as u64));
+            tracees.remove(&pid);
+            if tracees.is_empty() {
+                break;
+            }
+            continue;
         }
 
-        // If we've reached here, we're sure the `tracee` is alive and has hit a system call
-        
-        // Check if it's the "exit" invocation from the system call
-        if is_sys_exit { // If it is the "exit" invocation
-            unsafe {
+        // A fork/vfork/clone event stop encodes which event it was in the high byte of the wait
+        // status: `status >> 8 == SIGTRAP | (PTRACE_EVENT_xxx << 8)`, with `PTRACE_EVENT_FORK`,
+        // `PTRACE_EVENT_VFORK` and `PTRACE_EVENT_CLONE` being `1`, `2` and `3` respectively
+        let event: i64 = status >> 8;
+        let is_fork_event: bool =
+            event == (5 | (1 << 8)) || event == (5 | (2 << 8)) || event == (5 | (3 << 8));
+        if is_fork_event {
+            // `PTRACE_GETEVENTMSG` (request `0x4201`) reads the new child's pid out for us, so we can
+            // start tracing it without racing the child's own `PTRACE_TRACEME`/attach
+            let mut child_pid: u64 = 0;
+            _ = syscalls::sys_ptrace(0x4201, pid as i64, 0, &mut child_pid as *mut u64 as i64);
+
+            tracees.insert(child_pid);
+            _ = syscalls::sys_ptrace(24, child_pid as i64, 0, 0);
+            _ = syscalls::sys_ptrace(24, pid as i64, 0, 0);
+            continue;
+        }
+
+        // An exec event stop (`PTRACE_EVENT_EXEC`, `4`) needs its own case too: it's neither a
+        // fork/clone nor a real signal, it's just the kernel telling us the tracee replaced its
+        // image via `execve`. Falling through to the "real signal" branch below would read its
+        // stop signal as a plain `SIGTRAP` and re-inject that into the tracee on the next
+        // `PTRACE_SYSCALL` call, and since `SIGTRAP`'s default disposition is terminate+core dump,
+        // every traced process would be killed the instant it execs
+        let is_exec_event: bool = event == (5 | (4 << 8));
+        if is_exec_event {
+            _ = syscalls::sys_ptrace(24, pid as i64, 0, 0);
+            continue;
+        }
+
+        // With `PTRACE_O_TRACESYSGOOD` set, a genuine syscall-stop always reports as `SIGTRAP | 0x80`,
+        // so anything else in `(status >> 8) & 0xff` is a real signal delivered to the tracee
+        // (SIGSEGV, SIGINT, SIGCHLD, ...) and needs to be passed back through on the next
+        // `PTRACE_SYSCALL` instead of the `0` we've been hardcoding, otherwise the tracee never sees
+        // it and can hang or misbehave
+        let stopsig: i64 = (status >> 8) & 0xff;
+        if stopsig != (5 | 0x80) {
+            println!("[{}] --- {} ---", pid, syscalls::signal_name(stopsig as u64));
+            _ = syscalls::sys_ptrace(24, pid as i64, 0, stopsig);
+            continue;
+        }
+
+        // If we've reached here, we're sure this is a genuine syscall-stop, but not whether it's the
+        // `entry` or `exit` side of the call. `PTRACE_GET_SYSCALL_INFO` (request `0x420e`) tells us
+        // that directly via its `op` field instead of us having to guess with an alternating toggle,
+        // which desynchronizes the moment any non-syscall trap slips in between two syscall-stops
+        let syscall_info = syscalls::sys_ptrace_get_syscall_info(pid);
+        match syscall_info.op {
+            // We only have anything worth printing once the syscall has actually returned, so the
+            // entry side of the call (and a `NONE` stop, which isn't a syscall stop at all) are
+            // both no-ops here; the tracee just gets resumed below like every other stop
+            syscalls::PTRACE_SYSCALL_INFO_ENTRY | syscalls::PTRACE_SYSCALL_INFO_NONE => {}
+            syscalls::PTRACE_SYSCALL_INFO_EXIT => unsafe {
                 // Instantiage a new register state with all zeroes, this is what we'll copy the actual register values of the `tracee` into
                 let mut regs: syscalls::UserRegsStruct = std::mem::zeroed();
 
                 // Trigger a `ptrace` system call, this specific instance will read the values from the process registers and copy them
                 // in our newly creatd register state so we can access them
-                _ = syscalls::sys_ptrace(12, tracee_pid as i64, 0, &mut regs as *mut syscalls::UserRegsStruct as i64);
-
-                // Log the trace for this specific system call which includes the `pid`, `syscall name`, `first few arguments passed in`, `output`:
-                //
-                // ...
-                // [51942] write(1, 55a4553dacf0, 7, ...) = 7
-                // [51942] close(1, 55a4553dacf0, 7fae1174a8a0, ...) = 0
-                // [51942] close(2, fbad2006, 7fae1174a8a0, ...) = 0
-                // ...
-                //
-                println!(
-                    "[{}] {}({:x}, {:x}, {:x}, ...) = {:x}",
-                    tracee_pid,
-                    syscall_table[&regs.orig_rax],
-                    regs.rdi,
-                    regs.rsi,
-                    regs.rdx,
-                    regs.rax,
-                );
-            }
+                _ = syscalls::sys_ptrace(12, pid as i64, 0, &mut regs as *mut syscalls::UserRegsStruct as i64);
+
+                // Respect an `-e trace=...` filter if one was given: print only syscalls in the set, or
+                // everything except them for a negated (`!`) filter, or everything when there's no filter.
+                // Note `syscall_info.nr` is the union field the exit side reuses for the return value, not
+                // the syscall number, so the actual id to filter on has to come from `orig_rax` instead
+                let should_print = match &trace_filter {
+                    Some((negated, ids)) => ids.contains(&regs.orig_rax) != *negated,
+                    None => true,
+                };
+
+                if should_print {
+                    // `x86_64-syscalls.json` only annotates the syscalls we've bothered to describe,
+                    // not every syscall the kernel has, so fall back to a best-effort `SyscallInfo`
+                    // for anything missing instead of panicking the whole tracer via `HashMap::index`
+                    // the moment a real binary issues e.g. `fcntl`/`statx`
+                    let unknown_info = syscalls::unknown_syscall(regs.orig_rax);
+                    let info = syscall_table.get(&regs.orig_rax).unwrap_or(&unknown_info);
+
+                    // The argument registers in calling-convention order, note the 4th argument lives
+                    // in `r10` rather than `rcx` (the syscall instruction clobbers `rcx` with the return
+                    // address), which `UserRegsStruct` already exposes under its own field
+                    let arg_regs = [regs.rdi, regs.rsi, regs.rdx, regs.r10, regs.r8, regs.r9];
+
+                    // Format exactly as many arguments as this syscall actually takes, per its real
+                    // arity and semantics, instead of always printing a fixed three hex columns
+                    let args: Vec<String> = info
+                        .args
+                        .iter()
+                        .enumerate()
+                        .map(|(i, arg_type)| {
+                            let value = arg_regs[i];
+                            match arg_type {
+                                syscalls::ArgType::Int => format!("{}", value as i64),
+                                syscalls::ArgType::Fd => format!("{}", value as i64),
+                                syscalls::ArgType::Flags => format!("{:#x}", value),
+                                syscalls::ArgType::Ptr => format!("{:#x}", value),
+                                syscalls::ArgType::Str => {
+                                    let bytes = syscalls::read_tracee_memory(pid, value, syscalls::MAX_TRACE_READ_LEN, true);
+                                    format!("\"{}\"", syscalls::escape_bytes(&bytes))
+                                }
+                                syscalls::ArgType::Buf(len_arg_index) => {
+                                    // Bounded by the sibling `count`/`len` register, not a NUL, since
+                                    // a sized buffer like `write`'s `buf` can legitimately contain
+                                    // embedded NUL bytes that are part of the actual data. Capped the
+                                    // same way `Str` is above: the register can hold a huge or
+                                    // negative/`SIZE_MAX` count (a large legitimate I/O buffer, or a
+                                    // failed/edge-case call), and reading that many bytes a word at a
+                                    // time would either blow up `Vec::with_capacity` or cost thousands
+                                    // of `PTRACE_PEEKDATA` calls for one trace line
+                                    let len = (arg_regs[*len_arg_index] as usize).min(syscalls::MAX_TRACE_READ_LEN);
+                                    let bytes = syscalls::read_tracee_memory(pid, value, len, false);
+                                    format!("\"{}\"", syscalls::escape_bytes(&bytes))
+                                }
+                            }
+                        })
+                        .collect();
+
+                    // Log the trace for this specific system call which includes the `pid`, `syscall name`, the arguments it actually takes, `output`:
+                    //
+                    // ...
+                    // [51942] write(1, "hello\n", 6) = 6
+                    // [51942] open("/etc/hosts", 0x0, 0) = 3
+                    // [51943] close(2) = 0
+                    // ...
+                    //
+                    // Note the pid in each line: with fork/clone tracking in place it's no longer always
+                    // `tracee_pid`, it's whichever tracee actually triggered the syscall
+                    println!(
+                        "[{}] {}({}) = {:x}",
+                        pid,
+                        info.name,
+                        args.join(", "),
+                        regs.rax,
+                    );
+                }
+            },
+            _ => {} // `PTRACE_SYSCALL_INFO_SECCOMP` or anything else we don't special-case
         }
 
-        // Toggle the flag
-        is_sys_exit = !is_sys_exit;
+        // Keep this tracee running until its next syscall stop
+        _ = syscalls::sys_ptrace(24, pid as i64, 0, 0);
     }
 }
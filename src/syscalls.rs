@@ -30,25 +30,88 @@ pub struct UserRegsStruct { // These are all the registers that can be interacte
     pub gs: u64,
 }
 
-pub fn fetch_syscall_table() -> std::collections::HashMap<u64, String> {
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArgType { // How a syscall argument should be read/formatted for the trace, driven by `x86_64-syscalls.json`
+    Int,   // A plain integer, printed as decimal
+    Ptr,   // An opaque pointer that isn't further decoded, printed as hex
+    Str,   // A pointer to a NUL-terminated string, read out of the tracee's memory
+    Fd,    // A file descriptor, printed as decimal
+    Flags, // A bitmask, printed as hex
+
+    // A pointer to a sized buffer, e.g. the `buf` of `write(fd, buf, count)`, that isn't a
+    // NUL-terminated C string: the `usize` carried here is the index (within the same syscall's
+    // `args`) of the sibling argument holding the real length, so the reader uses that many bytes
+    // verbatim instead of guessing where the buffer ends from a NUL that may not be there, or may
+    // be there early, as actual binary data can contain
+    Buf(usize),
+}
+
+#[derive(Debug, Clone)]
+pub struct SyscallInfo {
+    pub name: String,
+    pub args: Vec<ArgType>,
+}
+
+// Caps how many bytes of tracee memory a single `Str`/`Buf` argument will read, so one trace line
+// never needs thousands of `PTRACE_PEEKDATA` calls (a word at a time) to print a single large or
+// bogus (e.g. `-1`/`SIZE_MAX`) length
+pub const MAX_TRACE_READ_LEN: usize = 256;
+
+// `x86_64-syscalls.json` only annotates the syscalls we actually care about in a trace, not every
+// syscall the kernel exposes, so any id missing from the table (e.g. `fcntl`, `madvise`, `statx`,
+// which real binaries issue all the time) falls back to this
+// rather than panicking the whole tracer via `HashMap::index`
+pub fn unknown_syscall(id: u64) -> SyscallInfo {
+    SyscallInfo {
+        name: format!("syscall_{}", id),
+        args: Vec::new(),
+    }
+}
+
+pub fn fetch_syscall_table() -> std::collections::HashMap<u64, SyscallInfo> {
     // Read the corresponding file which holds the system call mappings and serialize them into JSON
     let syscall_json: serde_json::Value = serde_json::from_str(include_str!("x86_64-syscalls.json"))
         .expect("unable to parse syscalls json");
 
-    // Parse the JSON into key-value pair mappings where each system call id is mapped to it's name
-    // i.e. 0 -> read, 1 -> write ...
-    let syscall_table: std::collections::HashMap<u64, String> = syscall_json["data"]
+    // Parse the JSON into key-value pair mappings where each system call id is mapped to its name
+    // and the types of the arguments it takes, i.e. 0 -> read(fd, buf, count) ...
+    let syscall_table: std::collections::HashMap<u64, SyscallInfo> = syscall_json["data"]
         .as_array()
         .unwrap()
         .iter()
         .map(|item| {
-            (
-                item[0].as_u64().unwrap(),
-                item[1].as_str().unwrap().to_owned(),
-            )
+            let id = item[0].as_u64().unwrap();
+            let name = item[1].as_str().unwrap().to_owned();
+
+            // The argument types are optional in the schema, syscalls we haven't annotated yet
+            // just fall back to printing nothing beyond the name. Most entries are a plain type
+            // name, but a sized-buffer argument is a `["buf", <index>]` pair instead, where
+            // `<index>` points back at the sibling argument that carries the buffer's real length
+            let args = item
+                .get(2)
+                .and_then(|args| args.as_array())
+                .map(|args| {
+                    args.iter()
+                        .map(|arg_type| match arg_type.as_str() {
+                            Some("ptr") => ArgType::Ptr,
+                            Some("str") => ArgType::Str,
+                            Some("fd") => ArgType::Fd,
+                            Some("flags") => ArgType::Flags,
+                            Some(_) => ArgType::Int,
+                            None => {
+                                let pair = arg_type.as_array().expect("arg type must be a string or a [\"buf\", index] pair");
+                                let len_arg_index = pair[1].as_u64().unwrap() as usize;
+                                ArgType::Buf(len_arg_index)
+                            }
+                        })
+                        .collect()
+                })
+                .unwrap_or_default();
+
+            (id, SyscallInfo { name, args })
         })
         .collect();
-    
+
     return syscall_table;
 }
 
@@ -138,3 +201,171 @@ pub fn sys_wait4(rdi: i64, rsi: i64, rdx: i64, r10: i64) -> i64 {
 
     return sys_retval;
 }
+
+pub fn read_tracee_memory(pid: u64, addr: u64, len: usize, stop_at_nul: bool) -> Vec<u8> {
+    // `ptrace` has no "read N bytes" request, `PTRACE_PEEKDATA` (request `2`) only ever reads a
+    // single machine word (8 bytes) at a time out of the tracee's address space, and at the raw
+    // syscall level (unlike the libc wrapper) the kernel writes that word to the address passed as
+    // `data` rather than returning it, so we walk `addr` a word at a time until we've collected `len` bytes. `stop_at_nul` bounds a
+    // NUL-terminated C string the same way a C string reader would; a sized buffer (e.g. `write`'s
+    // `buf`, bounded by its own `count` argument rather than a NUL) passes `false` so embedded NUL
+    // bytes that are genuinely part of the data don't truncate the read early
+    let mut bytes: Vec<u8> = Vec::with_capacity(len);
+
+    'words: while bytes.len() < len {
+        let mut word: i64 = 0;
+        _ = sys_ptrace(2, pid as i64, (addr + bytes.len() as u64) as i64, &mut word as *mut i64 as i64);
+        for shift in (0..64).step_by(8) {
+            if bytes.len() >= len {
+                break 'words;
+            }
+
+            let byte = ((word >> shift) & 0xff) as u8;
+            if stop_at_nul && byte == 0 {
+                break 'words;
+            }
+
+            bytes.push(byte);
+        }
+    }
+
+    return bytes;
+}
+
+pub fn escape_bytes(bytes: &[u8]) -> String {
+    // Render printable bytes verbatim and escape everything else the way `strace` does, so binary
+    // buffers show up as `\xNN` instead of garbling the terminal, and truncate long buffers since a
+    // single `read`/`write` of a few kilobytes would otherwise swamp the rest of the trace line
+    const MAX_LEN: usize = 32;
+
+    let mut escaped = String::new();
+    for &byte in bytes.iter().take(MAX_LEN) {
+        match byte {
+            b'\n' => escaped.push_str("\\n"),
+            b'\t' => escaped.push_str("\\t"),
+            b'\r' => escaped.push_str("\\r"),
+            b'"' => escaped.push_str("\\\""),
+            b'\\' => escaped.push_str("\\\\"),
+            0x20..=0x7e => escaped.push(byte as char), // Printable ASCII
+            _ => escaped.push_str(&format!("\\x{:02x}", byte)),
+        }
+    }
+
+    if bytes.len() > MAX_LEN {
+        escaped.push_str("...");
+    }
+
+    return escaped;
+}
+
+pub fn signal_name(sig: u64) -> &'static str {
+    // Map the handful of signals a tracee commonly gets hit with to their names, for printing
+    // `--- SIG<name> ---` lines the same way `strace` annotates delivered signals
+    match sig {
+        1 => "SIGHUP",
+        2 => "SIGINT",
+        3 => "SIGQUIT",
+        4 => "SIGILL",
+        6 => "SIGABRT",
+        7 => "SIGBUS",
+        8 => "SIGFPE",
+        9 => "SIGKILL",
+        10 => "SIGUSR1",
+        11 => "SIGSEGV",
+        12 => "SIGUSR2",
+        13 => "SIGPIPE",
+        14 => "SIGALRM",
+        15 => "SIGTERM",
+        17 => "SIGCHLD",
+        18 => "SIGCONT",
+        19 => "SIGSTOP",
+        20 => "SIGTSTP",
+        21 => "SIGTTIN",
+        22 => "SIGTTOU",
+        _ => "SIGUNKNOWN",
+    }
+}
+
+pub const PTRACE_SYSCALL_INFO_NONE: u8 = 0;
+pub const PTRACE_SYSCALL_INFO_ENTRY: u8 = 1;
+pub const PTRACE_SYSCALL_INFO_EXIT: u8 = 2;
+
+#[repr(C)]
+#[derive(Debug)]
+pub struct PtraceSyscallInfo { // Mirrors the kernel's `struct ptrace_syscall_info`, used with `PTRACE_GET_SYSCALL_INFO`
+    pub op: u8, // `PTRACE_SYSCALL_INFO_{NONE,ENTRY,EXIT,SECCOMP}`, tells us authoritatively which stop this is
+    pad: [u8; 3],
+    pub arch: u32,
+    pub instruction_pointer: u64,
+    pub stack_pointer: u64,
+    // The kernel struct unions an `entry`/`exit`/`seccomp` variant here, all of which start with an
+    // 8-byte field (`nr` on entry, the syscall return value `rval` on exit), so `nr` doubles as
+    // `rval` depending on `op`, we never need `entry.args`/`seccomp` since we fetch those via `GETREGS`
+    pub nr: u64,
+}
+
+// The two convenience groups `-e trace=file` and `-e trace=network` expand to, mirroring the
+// groups `strace -e trace=file`/`-e trace=network` offers, so users don't have to spell out every
+// name in the group themselves
+const FILE_SYSCALLS: &[&str] = &[
+    "open", "openat", "stat", "fstat", "lstat", "access", "unlink", "unlinkat", "mkdir", "mkdirat",
+    "rmdir", "rename", "renameat", "link", "linkat", "symlink", "readlink", "chmod", "fchmod",
+    "chown", "fchown", "truncate", "ftruncate", "getcwd", "chdir", "close",
+];
+const NETWORK_SYSCALLS: &[&str] = &[
+    "socket", "connect", "accept", "accept4", "bind", "listen", "send", "sendto", "sendmsg",
+    "recv", "recvfrom", "recvmsg", "shutdown", "getsockname", "getpeername", "setsockopt",
+    "getsockopt",
+];
+
+pub fn parse_trace_filter(
+    expr: &str,
+    table: &std::collections::HashMap<u64, SyscallInfo>,
+) -> (bool, std::collections::HashSet<u64>) {
+    // `expr` is the raw string after `-e`, e.g. `trace=open,read,write` or `trace=!close`, so strip
+    // the `trace=` prefix the same way `strace -e trace=...` does
+    let expr = expr.strip_prefix("trace=").unwrap_or(expr);
+
+    // A leading `!` negates the whole set, e.g. `trace=!close` means "everything except close",
+    // same convention `strace` uses
+    let (negated, expr) = match expr.strip_prefix('!') {
+        Some(rest) => (true, rest),
+        None => (false, expr),
+    };
+
+    // Reverse-map names to ids through the syscall table built at startup, expanding the `file` and
+    // `network` convenience groups into their member syscalls along the way
+    let mut names: std::collections::HashSet<&str> = std::collections::HashSet::new();
+    for token in expr.split(',') {
+        match token {
+            "file" => names.extend(FILE_SYSCALLS),
+            "network" => names.extend(NETWORK_SYSCALLS),
+            name => {
+                names.insert(name);
+            }
+        }
+    }
+
+    let ids = table
+        .iter()
+        .filter(|(_, info)| names.contains(info.name.as_str()))
+        .map(|(id, _)| *id)
+        .collect();
+
+    return (negated, ids);
+}
+
+pub fn sys_ptrace_get_syscall_info(pid: u64) -> PtraceSyscallInfo {
+    let mut info: PtraceSyscallInfo = unsafe { std::mem::zeroed() };
+
+    // `PTRACE_GET_SYSCALL_INFO` (request `0x420e`) wants the size of the buffer in the `addr`
+    // argument and a pointer to the buffer itself in `data`
+    _ = sys_ptrace(
+        0x420e,
+        pid as i64,
+        std::mem::size_of::<PtraceSyscallInfo>() as i64,
+        &mut info as *mut PtraceSyscallInfo as i64,
+    );
+
+    return info;
+}